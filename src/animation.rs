@@ -0,0 +1,115 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::AnimationDecoder;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use webp::{AnimEncoder, AnimFrame, WebPConfig};
+
+/// A single decoded animation frame, already composited onto the full
+/// canvas (the `image` crate's frame decoders resolve GIF/APNG disposal
+/// methods internally, so every frame here is a complete RGBA image).
+pub struct Frame {
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub delay_ms: u32,
+}
+
+/// Decodes `path` as a multi-frame GIF or APNG. Returns `None` for anything
+/// that isn't an animation (including single-frame GIFs and plain PNGs), so
+/// callers fall back to the static single-frame path.
+pub fn decode_frames(path: &Path) -> Option<(Vec<Frame>, i32)> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "gif" => decode_gif(path),
+        "png" | "apng" => decode_apng(path),
+        _ => None,
+    }
+}
+
+fn decode_gif(path: &Path) -> Option<(Vec<Frame>, i32)> {
+    let file = File::open(path).ok()?;
+    let decoder = GifDecoder::new(BufReader::new(file)).ok()?;
+    let frames = collect_frames(decoder)?;
+    // The GIF format has no per-file loop count field exposed by the
+    // decoder; browsers treat GIFs as looping forever, so we do too.
+    Some((frames, 0))
+}
+
+fn decode_apng(path: &Path) -> Option<(Vec<Frame>, i32)> {
+    let file = File::open(path).ok()?;
+    let decoder = PngDecoder::new(BufReader::new(file)).ok()?;
+    if !decoder.is_apng().ok()? {
+        return None;
+    }
+    let apng_decoder = decoder.apng().ok()?;
+    let frames = collect_frames(apng_decoder)?;
+    Some((frames, 0))
+}
+
+fn collect_frames<D: AnimationDecoder<'static>>(decoder: D) -> Option<Vec<Frame>> {
+    let frames: Vec<image::Frame> = decoder.into_frames().collect::<Result<_, _>>().ok()?;
+    if frames.len() <= 1 {
+        return None;
+    }
+
+    Some(
+        frames
+            .into_iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                let delay_ms = if denom == 0 {
+                    numer
+                } else {
+                    numer / denom.max(1)
+                };
+                let buffer = frame.into_buffer();
+                Frame {
+                    width: buffer.width(),
+                    height: buffer.height(),
+                    delay_ms,
+                    rgba: buffer.into_raw(),
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Encodes decoded frames as an animated WebP, preserving per-frame timing
+/// and loop count.
+///
+/// `webp::AnimEncoder::encode` derives each frame's on-screen duration from
+/// the gap to the *next* frame's timestamp, and flushes the terminating gap
+/// itself at timestamp 0 — so without help, the last real frame would end
+/// up with a bogus (negative) duration instead of its own `delay_ms`. We
+/// work around this by re-adding the last frame's image at the timestamp it
+/// should end at: an identical-looking frame the viewer never perceives,
+/// whose only job is to give the true last frame a correct duration.
+pub fn encode(frames: &[Frame], loop_count: i32, quality: u8) -> Option<Vec<u8>> {
+    let first = frames.first()?;
+    let last = frames.last()?;
+    let mut config = WebPConfig::new().ok()?;
+    config.quality = quality as f32;
+
+    let mut encoder = AnimEncoder::new(first.width, first.height, &config);
+    encoder.set_loop_count(loop_count);
+
+    let mut timestamp_ms: i32 = 0;
+    for frame in frames {
+        encoder.add_frame(AnimFrame::from_rgba(
+            &frame.rgba,
+            frame.width,
+            frame.height,
+            timestamp_ms,
+        ));
+        timestamp_ms += frame.delay_ms as i32;
+    }
+    encoder.add_frame(AnimFrame::from_rgba(
+        &last.rgba,
+        last.width,
+        last.height,
+        timestamp_ms,
+    ));
+
+    Some(encoder.encode().to_vec())
+}
@@ -0,0 +1,166 @@
+use crate::{encode_webp, normalize_for_encode};
+use humansize::{FormatSizeOptions, SizeFormatter};
+use image::{DynamicImage, GenericImageView};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Per-image timing and size collected while benchmarking.
+struct Sample {
+    original_bytes: u64,
+    webp_bytes: u64,
+    megapixels: f64,
+    encode_time: Duration,
+}
+
+/// Times the encode step for every entry and prints aggregate throughput
+/// and compression statistics. Nothing is written to disk.
+pub fn run(entries: &[walkdir::DirEntry], quality: u8) {
+    let wall_start = Instant::now();
+    let mut samples = Vec::new();
+
+    for entry in entries {
+        let path = entry.path();
+
+        let bytes = match std::fs::read(path) {
+            Ok(b) => b,
+            Err(_) => {
+                eprintln!("Failed to read file: {:?}", path);
+                continue;
+            }
+        };
+
+        let Some(img) = decode(path) else { continue };
+        let (width, height) = img.dimensions();
+        let megapixels = (width as f64 * height as f64) / 1_000_000.0;
+
+        let start = Instant::now();
+        let encoded = encode_webp(&img, quality);
+        let elapsed = start.elapsed();
+        // Keep the optimizer from proving the encode output is unused and
+        // eliding the call.
+        let Some(encoded) = black_box(encoded) else {
+            continue;
+        };
+
+        samples.push(Sample {
+            original_bytes: bytes.len() as u64,
+            webp_bytes: encoded.len() as u64,
+            megapixels,
+            encode_time: elapsed,
+        });
+    }
+
+    let wall_time = wall_start.elapsed();
+
+    if samples.is_empty() {
+        println!("No images to benchmark.");
+        return;
+    }
+
+    let total_megapixels: f64 = samples.iter().map(|s| s.megapixels).sum();
+    let total_original_bytes: u64 = samples.iter().map(|s| s.original_bytes).sum();
+    let total_encode_secs: f64 = samples.iter().map(|s| s.encode_time.as_secs_f64()).sum();
+    let mean_ratio: f64 = samples
+        .iter()
+        .map(|s| s.webp_bytes as f64 / s.original_bytes.max(1) as f64)
+        .sum::<f64>()
+        / samples.len() as f64;
+
+    let mp_per_sec = if total_encode_secs > 0.0 {
+        total_megapixels / total_encode_secs
+    } else {
+        0.0
+    };
+    let mb_per_sec = if total_encode_secs > 0.0 {
+        (total_original_bytes as f64 / 1_000_000.0) / total_encode_secs
+    } else {
+        0.0
+    };
+
+    println!("\n--- Benchmark ({} images) ---", samples.len());
+    println!(
+        "Encode throughput: {:.2} MP/s, {:.2} MB/s",
+        mp_per_sec, mb_per_sec
+    );
+    println!("Mean compression ratio: {:.2}%", mean_ratio * 100.0);
+    println!("Total wall time:   {:.2}s", wall_time.as_secs_f64());
+}
+
+/// Encodes every entry at each quality in `qualities` and prints a table of
+/// the resulting average size and PSNR, so a corpus owner can pick a
+/// quality target empirically.
+pub fn run_sweep(entries: &[walkdir::DirEntry], qualities: &[u8]) {
+    println!("\n--- Quality sweep ---");
+    println!("{:>8}  {:>12}  {:>10}", "Quality", "Avg Size", "Avg PSNR");
+
+    for &quality in qualities {
+        let mut total_size = 0u64;
+        let mut total_psnr = 0.0;
+        let mut psnr_count = 0u64;
+        let mut count = 0u64;
+
+        for entry in entries {
+            let Some(img) = decode(entry.path()) else {
+                continue;
+            };
+            let Some(encoded) = encode_webp(&img, quality) else {
+                continue;
+            };
+
+            total_size += encoded.len() as u64;
+            if let Some(psnr) = psnr_against(&img, &encoded) {
+                total_psnr += psnr;
+                psnr_count += 1;
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            continue;
+        }
+
+        println!(
+            "{:>8}  {:>12}  {:>9.2}dB",
+            quality,
+            SizeFormatter::new(total_size / count, FormatSizeOptions::default()),
+            total_psnr / psnr_count.max(1) as f64
+        );
+    }
+}
+
+fn decode(path: &std::path::Path) -> Option<DynamicImage> {
+    let img = image::ImageReader::open(path).ok()?.decode().ok()?;
+    Some(normalize_for_encode(img))
+}
+
+/// Peak signal-to-noise ratio between the original image and its re-decoded
+/// webp encoding, in decibels. Returns `None` if the webp can't be decoded
+/// back or its dimensions don't match (both mean a pixel-by-pixel comparison
+/// isn't meaningful here).
+fn psnr_against(original: &DynamicImage, encoded: &[u8]) -> Option<f64> {
+    let decoded = webp::Decoder::new(encoded).decode()?.to_image();
+    let original_rgba = original.to_rgba8();
+    let decoded_rgba = decoded.to_rgba8();
+
+    if original_rgba.dimensions() != decoded_rgba.dimensions() {
+        return None;
+    }
+
+    let samples = original_rgba.as_raw().len();
+    let mse: f64 = original_rgba
+        .as_raw()
+        .iter()
+        .zip(decoded_rgba.as_raw().iter())
+        .map(|(&a, &b)| {
+            let diff = a as f64 - b as f64;
+            diff * diff
+        })
+        .sum::<f64>()
+        / samples as f64;
+
+    if mse == 0.0 {
+        return Some(f64::INFINITY);
+    }
+
+    Some(10.0 * (255.0 * 255.0 / mse).log10())
+}
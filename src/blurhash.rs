@@ -0,0 +1,154 @@
+use image::DynamicImage;
+
+const BASE83_CHARS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Longest side the image is downscaled to before encoding. BlurHash only
+/// ever extracts a handful of low-frequency basis components, so running
+/// the DCT-like sum over a full-resolution photo buys no extra fidelity —
+/// just cost; a small thumbnail produces a practically identical placeholder.
+const THUMBNAIL_MAX_DIM: u32 = 64;
+
+/// Encodes an image as a BlurHash string using `x_components` by
+/// `y_components` basis functions (each in `1..=9`).
+///
+/// Each component's color is `(1/N) * sum(pixel * cos(pi*cx*x/width) *
+/// cos(pi*cy*y/height))` over linear RGB, with the DC term (component 0,0)
+/// weighted 1.0 and AC terms weighted 2.0. The DC term becomes the average
+/// color; the AC terms are quantized against the largest AC magnitude.
+pub fn encode(img: &DynamicImage, x_components: u32, y_components: u32) -> String {
+    let rgb = img
+        .thumbnail(THUMBNAIL_MAX_DIM, THUMBNAIL_MAX_DIM)
+        .to_rgb8();
+    let (width, height) = rgb.dimensions();
+
+    // Each pixel's basis weight factors into `cos_x[cx][x] * cos_y[cy][y]`;
+    // precomputing both axes once turns the per-pixel cost from several
+    // `cos` calls per component into a couple of array lookups.
+    let cos_x = cos_table(x_components, width);
+    let cos_y = cos_table(y_components, height);
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for cy in 0..y_components {
+        for cx in 0..x_components {
+            factors.push(component(&rgb, width, height, cx, cy, &cos_x, &cos_y));
+        }
+    }
+
+    let mut result = String::new();
+
+    let size_flag = (x_components - 1) + (y_components - 1) * 9;
+    result.push_str(&base83_encode(size_flag as u64, 1));
+
+    let ac_count = factors.len() - 1;
+    let max_value = if ac_count > 0 {
+        let actual_max = factors[1..]
+            .iter()
+            .flat_map(|&[r, g, b]| [r.abs(), g.abs(), b.abs()])
+            .fold(0.0f64, f64::max);
+        let quantized = ((actual_max * 166.0 - 0.5).floor() as i64).clamp(0, 82);
+        result.push_str(&base83_encode(quantized as u64, 1));
+        (quantized as f64 + 1.0) / 166.0
+    } else {
+        result.push_str(&base83_encode(0, 1));
+        1.0
+    };
+
+    result.push_str(&base83_encode(encode_dc(factors[0]), 4));
+
+    for &ac in &factors[1..] {
+        result.push_str(&base83_encode(encode_ac(ac, max_value), 2));
+    }
+
+    result
+}
+
+/// Precomputes `cos(pi * c * i / size)` for every `(component, pixel)` pair
+/// along one axis, indexed `[c][i]`.
+fn cos_table(components: u32, size: u32) -> Vec<Vec<f64>> {
+    (0..components)
+        .map(|c| {
+            (0..size)
+                .map(|i| (std::f64::consts::PI * c as f64 * i as f64 / size as f64).cos())
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes a single `(cx, cy)` basis component's average linear-RGB color.
+fn component(
+    rgb: &image::RgbImage,
+    width: u32,
+    height: u32,
+    cx: u32,
+    cy: u32,
+    cos_x: &[Vec<f64>],
+    cos_y: &[Vec<f64>],
+) -> [f64; 3] {
+    let normalization = if cx == 0 && cy == 0 { 1.0 } else { 2.0 };
+    let mut sum = [0.0f64; 3];
+
+    for y in 0..height {
+        let cos_y = cos_y[cy as usize][y as usize];
+        for x in 0..width {
+            let basis = cos_x[cx as usize][x as usize] * cos_y;
+            let pixel = rgb.get_pixel(x, y);
+            sum[0] += basis * srgb_to_linear(pixel[0]);
+            sum[1] += basis * srgb_to_linear(pixel[1]);
+            sum[2] += basis * srgb_to_linear(pixel[2]);
+        }
+    }
+
+    let scale = normalization / (width as f64 * height as f64);
+    [sum[0] * scale, sum[1] * scale, sum[2] * scale]
+}
+
+fn encode_dc(color: [f64; 3]) -> u64 {
+    let r = linear_to_srgb(color[0]) as u64;
+    let g = linear_to_srgb(color[1]) as u64;
+    let b = linear_to_srgb(color[2]) as u64;
+    (r << 16) + (g << 8) + b
+}
+
+fn encode_ac(color: [f64; 3], max_value: f64) -> u64 {
+    let quant = |value: f64| -> u64 {
+        (sign_pow(value / max_value, 0.5) * 9.0 + 9.5)
+            .floor()
+            .clamp(0.0, 18.0) as u64
+    };
+    quant(color[0]) * 19 * 19 + quant(color[1]) * 19 + quant(color[2])
+}
+
+fn sign_pow(value: f64, exp: f64) -> f64 {
+    value.signum() * value.abs().powf(exp)
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn base83_encode(value: u64, length: usize) -> String {
+    let mut result = vec![0u8; length];
+    let mut value = value;
+    for i in (0..length).rev() {
+        let digit = (value % 83) as usize;
+        result[i] = BASE83_CHARS[digit];
+        value /= 83;
+    }
+    String::from_utf8(result).unwrap()
+}
@@ -1,19 +1,51 @@
+mod animation;
+mod benchmark;
+mod blurhash;
+mod manifest;
+mod phash;
+mod pipeline;
+mod prune;
+
 use argh::FromArgs;
 use humansize::FormatSizeOptions;
 use humansize::SizeFormatter;
 use image::DynamicImage;
+use image::GenericImageView;
 use image::ImageReader;
-use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelIterator;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
 use std::{
     fs::{self, File},
-    io::Read,
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
+/// Deduplication strategy used to decide whether an image has already been
+/// encoded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DedupMode {
+    /// Byte-identical inputs share an output (the default).
+    Exact,
+    /// Visually-identical inputs share an output, matched via pHash.
+    Perceptual,
+}
+
+impl FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(DedupMode::Exact),
+            "perceptual" => Ok(DedupMode::Perceptual),
+            other => Err(format!(
+                "unknown dedup mode: {other} (expected exact or perceptual)"
+            )),
+        }
+    }
+}
+
 /// Optimize images to webp format with deduplication.
 #[derive(FromArgs)]
 struct Cli {
@@ -28,17 +60,125 @@ struct Cli {
     /// webp quality (0-100)
     #[argh(option, default = "75")]
     quality: u8,
+
+    /// deduplication mode: "exact" (default) or "perceptual"
+    #[argh(option, default = "DedupMode::Exact")]
+    dedup: DedupMode,
+
+    /// max Hamming distance for a perceptual-hash match (default 5)
+    #[argh(option, default = "5")]
+    threshold: u32,
+
+    /// write a JSON sidecar manifest (path, hash, size, dimensions, BlurHash)
+    /// for every processed image
+    #[argh(option)]
+    manifest: Option<String>,
+
+    /// time the encode step instead of writing output, reporting throughput
+    /// and compression statistics
+    #[argh(switch)]
+    benchmark: bool,
+
+    /// comma-separated quality values to sweep (used with --benchmark),
+    /// e.g. "50,75,90"
+    #[argh(option)]
+    sweep: Option<String>,
+
+    /// after processing, delete `{hash}.webp` files no longer referenced by
+    /// any current input
+    #[argh(switch)]
+    prune: bool,
+}
+
+/// Converts 8-bit grayscale (with or without alpha) to RGB(A), since the
+/// webp encoder only accepts color images. Other variants pass through as-is.
+fn normalize_for_encode(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(ref gray) => {
+            DynamicImage::ImageRgb8(DynamicImage::ImageLuma8(gray.clone()).to_rgb8())
+        }
+        DynamicImage::ImageLumaA8(ref gray_alpha) => {
+            DynamicImage::ImageRgba8(DynamicImage::ImageLumaA8(gray_alpha.clone()).to_rgba8())
+        }
+        _ => img,
+    }
+}
+
+/// Encodes an image as webp, returning `None` if the encoder rejects it.
+fn encode_webp(img: &DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    let encoder = webp::Encoder::from_image(img).ok()?;
+    Some(encoder.encode(quality as f32).to_vec())
+}
+
+/// Encodes `path` as an animated webp if it's a multi-frame GIF/APNG,
+/// otherwise falls back to the normal single-frame encode of `img`.
+fn encode_output(path: &Path, img: &DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    if let Some((frames, loop_count)) = animation::decode_frames(path) {
+        if let Some(bytes) = animation::encode(&frames, loop_count, quality) {
+            return Some(bytes);
+        }
+    }
+    encode_webp(img, quality)
+}
+
+/// Writes `webp_bytes` to `webp_path` (or touches an empty placeholder when
+/// the webp didn't end up smaller) and returns the byte count to attribute
+/// to the running total.
+fn write_webp(webp_path: &Path, webp_bytes: &[u8], original_len: u64) -> u64 {
+    if (webp_bytes.len() as u64) < original_len {
+        if let Ok(mut out) = File::create(webp_path) {
+            let _ = out.write_all(webp_bytes);
+        }
+        webp_bytes.len() as u64
+    } else {
+        let _ = File::create(webp_path);
+        original_len
+    }
+}
+
+/// Formats `path` relative to `input_dir` for use as a manifest key.
+fn relative_path(input_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(input_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Decodes `path` and normalizes it for encoding. Used for every image the
+/// pipeline decodes, including manifest back-fill on an exact-dedup cache hit.
+fn decode_for_manifest(path: &Path) -> Option<DynamicImage> {
+    let img = ImageReader::open(path).ok()?.decode().ok()?;
+    Some(normalize_for_encode(img))
+}
+
+/// Appends a manifest entry when `--manifest` was requested; a no-op
+/// otherwise.
+fn record_manifest_entry(
+    manifest_entries: &Option<Arc<Mutex<Vec<manifest::Entry>>>>,
+    path: String,
+    hash: String,
+    webp_size: u64,
+    img: &DynamicImage,
+) {
+    let Some(entries) = manifest_entries else {
+        return;
+    };
+    let (width, height) = img.dimensions();
+    let blurhash = blurhash::encode(img, 4, 3);
+    entries.lock().unwrap().push(manifest::Entry {
+        path,
+        hash,
+        webp_size,
+        width,
+        height,
+        blurhash,
+    });
 }
 
 fn main() {
     let cli: Cli = argh::from_env();
 
     let input_dir = Path::new(&cli.input);
-    let output_dir = Path::new(&cli.output);
-
-    if !output_dir.exists() {
-        fs::create_dir_all(output_dir).expect("Failed to create output directory");
-    }
 
     let entries: Vec<_> = walkdir::WalkDir::new(input_dir)
         .into_iter()
@@ -46,99 +186,63 @@ fn main() {
         .filter(|e| e.file_type().is_file())
         .collect();
 
-    // Use a Mutex to safely update totals from multiple threads
-    let total_original_bytes = Arc::new(Mutex::new(0u64));
-    let total_webp_bytes = Arc::new(Mutex::new(0u64));
-
-    entries.par_iter().for_each(|entry| {
-        let path = entry.path();
-
-        // Read file bytes
-        let mut file = match File::open(path) {
-            Ok(f) => f,
-            Err(_) => {
-                eprintln!("Failed to open file: {:?}", path);
-                return;
+    if cli.benchmark {
+        match &cli.sweep {
+            Some(sweep) => {
+                let qualities: Vec<u8> = sweep
+                    .split(',')
+                    .filter_map(|q| q.trim().parse().ok())
+                    .collect();
+                benchmark::run_sweep(&entries, &qualities);
             }
-        };
+            None => benchmark::run(&entries, cli.quality),
+        }
+        return;
+    }
 
-        let mut bytes = Vec::new();
+    let output_dir = Path::new(&cli.output);
 
-        if file.read_to_end(&mut bytes).is_err() {
-            eprintln!("Failed to read file: {:?}", path);
-            return;
-        }
+    if !output_dir.exists() {
+        fs::create_dir_all(output_dir).expect("Failed to create output directory");
+    }
 
-        {
-            let mut orig = total_original_bytes.lock().unwrap();
-            *orig += bytes.len() as u64;
-        }
+    // Use a Mutex to safely update totals from multiple threads
+    let total_original_bytes = Arc::new(Mutex::new(0u64));
+    let total_webp_bytes = Arc::new(Mutex::new(0u64));
 
-        // Calculate blake2b hash
-        let mut hasher = blake3::Hasher::new();
-        hasher.update(&bytes);
-        let hash = hasher.finalize().to_hex();
-        let webp_path = entry
-            .path()
-            .parent()
-            .unwrap()
-            .join("../")
-            .join(format!("{hash}.webp"));
-
-        if webp_path.exists() {
-            let webp_size = match fs::metadata(&webp_path) {
-                Ok(meta) => meta.len(),
-                Err(_) => 0,
-            };
-
-            let mut webp = total_webp_bytes.lock().unwrap();
-            if webp_size == 0 {
-                *webp += bytes.len() as u64;
-            } else {
-                *webp += webp_size;
-            }
-            return;
-        }
+    // Perceptual-hash mode keeps its own registry of hashes it has already
+    // encoded, since near-duplicates don't share a blake3 hash (and
+    // therefore don't share an output filename) the way exact dupes do.
+    let seen_hashes: Arc<Mutex<Vec<(u64, PathBuf)>>> = Arc::new(Mutex::new(Vec::new()));
 
-        println!("Processing: {:?}", path);
+    let manifest_entries: Option<Arc<Mutex<Vec<manifest::Entry>>>> = cli
+        .manifest
+        .as_ref()
+        .map(|_| Arc::new(Mutex::new(Vec::new())));
 
-        // Try to decode image
-        let img = ImageReader::open(path)
-            .expect("Failed to open image")
-            .decode()
-            .expect("Failed to decode image");
+    // Every webp path a current input still points to; anything else under
+    // the scanned directories is an orphan once --prune runs.
+    let referenced_paths: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
 
-        let img = match img {
-            DynamicImage::ImageLuma8(ref gray) => {
-                DynamicImage::ImageRgb8(DynamicImage::ImageLuma8(gray.clone()).to_rgb8())
-            }
-            DynamicImage::ImageLumaA8(ref gray_alpha) => {
-                DynamicImage::ImageRgba8(DynamicImage::ImageLumaA8(gray_alpha.clone()).to_rgba8())
-            }
-            _ => img,
-        };
-
-        // Encode as webp
-        let mut webp_bytes = Vec::new();
-        let encoder = match webp::Encoder::from_image(&img) {
-            Ok(enc) => enc,
-            Err(_) => return,
-        };
-        let encoded = encoder.encode(cli.quality as f32);
-        webp_bytes.extend_from_slice(&encoded);
-
-        if webp_bytes.len() < bytes.len() {
-            if let Ok(mut out) = File::create(&webp_path) {
-                let _ = out.write_all(&webp_bytes);
-            }
-            let mut webp = total_webp_bytes.lock().unwrap();
-            *webp += webp_bytes.len() as u64;
-        } else {
-            let _ = File::create(&webp_path);
-            let mut webp = total_webp_bytes.lock().unwrap();
-            *webp += bytes.len() as u64;
-        }
-    });
+    // Counts inputs that couldn't be read or decoded this run; a nonzero count means
+    // `referenced_paths` is incomplete, so pruning would risk deleting a
+    // still-needed webp whose input we simply failed to see this time.
+    let failures: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+
+    let shared = pipeline::Shared {
+        input_dir,
+        quality: cli.quality,
+        dedup: cli.dedup,
+        threshold: cli.threshold,
+        total_original_bytes: total_original_bytes.clone(),
+        total_webp_bytes: total_webp_bytes.clone(),
+        seen_hashes,
+        manifest_entries,
+        referenced_paths: referenced_paths.clone(),
+        failures: failures.clone(),
+    };
+    pipeline::run(&entries, &shared);
+    let manifest_entries = shared.manifest_entries;
 
     // Get totals from mutexes
     let total_original_bytes = *total_original_bytes.lock().unwrap();
@@ -170,4 +274,46 @@ fn main() {
         SizeFormatter::new(saved_bytes, FormatSizeOptions::default()),
         percent_saved
     );
+
+    if let Some(manifest_path) = &cli.manifest {
+        let entries_arc = manifest_entries.unwrap();
+        let entries = entries_arc.lock().unwrap();
+        manifest::write(Path::new(manifest_path), &entries).expect("Failed to write manifest");
+        println!(
+            "Manifest:       {} ({} entries)",
+            manifest_path,
+            entries.len()
+        );
+    }
+
+    if cli.prune {
+        let failures = *failures.lock().unwrap();
+        if failures > 0 {
+            println!(
+                "Pruned:         skipped ({failures} input file(s) failed to read or decode this run, \
+                 so the referenced set is incomplete)"
+            );
+        } else {
+            let referenced_paths = referenced_paths.lock().unwrap();
+
+            // Scan every directory a current input could have written its
+            // webp into, not just the ones something still references —
+            // otherwise a directory whose inputs all became cache hits (or
+            // whose own stale outputs have no input pointing at them
+            // anymore) is never visited and its orphans survive forever.
+            let mut scan_dirs: HashSet<PathBuf> = entries
+                .iter()
+                .filter_map(|e| e.path().parent())
+                .map(|parent| parent.join(".."))
+                .collect();
+            scan_dirs.insert(output_dir.to_path_buf());
+
+            let (removed_count, removed_bytes) = prune::run(&scan_dirs, &referenced_paths);
+            println!(
+                "Pruned:         {} file(s), {}",
+                removed_count,
+                SizeFormatter::new(removed_bytes, FormatSizeOptions::default())
+            );
+        }
+    }
 }
@@ -0,0 +1,22 @@
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// One processed image's metadata, written out as part of the `--manifest`
+/// sidecar so a site can render instant low-res previews before the WebP
+/// itself has loaded.
+#[derive(Serialize)]
+pub struct Entry {
+    pub path: String,
+    pub hash: String,
+    pub webp_size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: String,
+}
+
+/// Writes `entries` to `path` as a pretty-printed JSON array.
+pub fn write(path: &Path, entries: &[Entry]) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(entries).expect("Failed to serialize manifest");
+    fs::write(path, json)
+}
@@ -0,0 +1,99 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+/// Side length of the grayscale image fed into the DCT.
+const SIZE: usize = 32;
+/// Side length of the low-frequency block kept from the DCT output.
+const LOW_FREQ: usize = 8;
+
+/// Computes a 64-bit perceptual hash for an image.
+///
+/// The image is converted to grayscale, resized to 32x32, and run through a
+/// 2D DCT. The top-left 8x8 block of coefficients (excluding the DC term) is
+/// compared against its median to produce the hash bits, so visually similar
+/// images end up with hashes a small Hamming distance apart.
+pub fn compute(img: &DynamicImage) -> u64 {
+    let gray = img
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Lanczos3)
+        .to_luma8();
+
+    let mut pixels = [[0.0f64; SIZE]; SIZE];
+    for (y, row) in pixels.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = gray.get_pixel(x as u32, y as u32)[0] as f64;
+        }
+    }
+
+    let dct = dct_2d(&pixels);
+
+    let mut coefficients = Vec::with_capacity(LOW_FREQ * LOW_FREQ - 1);
+    for row in dct.iter().take(LOW_FREQ) {
+        for &value in row.iter().take(LOW_FREQ) {
+            coefficients.push(value);
+        }
+    }
+    // Drop the DC term (index 0, top-left corner).
+    coefficients.remove(0);
+
+    let median = median(&mut coefficients.clone());
+
+    let mut hash = 0u64;
+    for (i, &coeff) in coefficients.iter().enumerate() {
+        if coeff > median {
+            hash |= 1 << i;
+        }
+    }
+
+    hash
+}
+
+/// Number of differing bits between two hashes.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn dct_2d(pixels: &[[f64; SIZE]; SIZE]) -> [[f64; SIZE]; SIZE] {
+    let mut rows = [[0.0f64; SIZE]; SIZE];
+    for (row, values) in rows.iter_mut().zip(pixels.iter()) {
+        *row = dct_1d(values);
+    }
+
+    let mut result = [[0.0f64; SIZE]; SIZE];
+    for x in 0..SIZE {
+        let column: [f64; SIZE] = std::array::from_fn(|y| rows[y][x]);
+        let transformed = dct_1d(&column);
+        for (y, value) in transformed.into_iter().enumerate() {
+            result[y][x] = value;
+        }
+    }
+
+    result
+}
+
+fn dct_1d(values: &[f64; SIZE]) -> [f64; SIZE] {
+    let mut output = [0.0f64; SIZE];
+    for (u, out) in output.iter_mut().enumerate() {
+        let mut sum = 0.0;
+        for (x, &value) in values.iter().enumerate() {
+            sum +=
+                value * ((std::f64::consts::PI / SIZE as f64) * (x as f64 + 0.5) * u as f64).cos();
+        }
+        let scale = if u == 0 {
+            (1.0 / SIZE as f64).sqrt()
+        } else {
+            (2.0 / SIZE as f64).sqrt()
+        };
+        *out = sum * scale;
+    }
+    output
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    decode_for_manifest, encode_output, manifest, phash, record_manifest_entry, relative_path,
+    write_webp, DedupMode,
+};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use image::DynamicImage;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// State shared across the walk/decode/encode stages; it's what the old
+/// single `par_iter` closure used to capture.
+pub struct Shared<'a> {
+    pub input_dir: &'a Path,
+    pub quality: u8,
+    pub dedup: DedupMode,
+    pub threshold: u32,
+    pub total_original_bytes: Arc<Mutex<u64>>,
+    pub total_webp_bytes: Arc<Mutex<u64>>,
+    pub seen_hashes: Arc<Mutex<Vec<(u64, PathBuf)>>>,
+    pub manifest_entries: Option<Arc<Mutex<Vec<manifest::Entry>>>>,
+    pub referenced_paths: Arc<Mutex<HashSet<PathBuf>>>,
+    pub failures: Arc<Mutex<u64>>,
+}
+
+/// A file that's been read, hashed, and (since it wasn't already cached)
+/// decoded — ready for the encode stage.
+struct DecodeJob {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    hash: String,
+    webp_path: PathBuf,
+    img: DynamicImage,
+}
+
+/// Walks `entries` through a bounded producer/consumer pipeline: a walk
+/// stage feeds paths into a channel, decode workers read+hash+decode+normalize
+/// into a second channel, and encode workers encode+write. Bounding both
+/// channels to a small multiple of the worker count caps how many decoded
+/// (uncompressed) images can be resident at once, so peak memory is bounded
+/// by a fixed multiple of the worker count and no longer scales with
+/// directory size.
+pub fn run(entries: &[walkdir::DirEntry], shared: &Shared) {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+
+    let (path_tx, path_rx): (Sender<&walkdir::DirEntry>, Receiver<&walkdir::DirEntry>) =
+        bounded(workers * 4);
+    let (decoded_tx, decoded_rx): (Sender<DecodeJob>, Receiver<DecodeJob>) = bounded(workers * 2);
+
+    thread::scope(|scope| {
+        let walk_tx = path_tx.clone();
+        scope.spawn(move || {
+            for entry in entries {
+                if walk_tx.send(entry).is_err() {
+                    break;
+                }
+            }
+        });
+        drop(path_tx);
+
+        for _ in 0..workers {
+            let path_rx = path_rx.clone();
+            let decoded_tx = decoded_tx.clone();
+            scope.spawn(move || {
+                for entry in path_rx {
+                    decode_one(entry, shared, &decoded_tx);
+                }
+            });
+        }
+        drop(path_rx);
+        drop(decoded_tx);
+
+        for _ in 0..workers {
+            let decoded_rx = decoded_rx.clone();
+            scope.spawn(move || {
+                for job in decoded_rx {
+                    encode_one(job, shared);
+                }
+            });
+        }
+        drop(decoded_rx);
+    });
+}
+
+fn decode_one(entry: &walkdir::DirEntry, shared: &Shared, decoded_tx: &Sender<DecodeJob>) {
+    let path = entry.path();
+
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => {
+            eprintln!("Failed to open file: {:?}", path);
+            *shared.failures.lock().unwrap() += 1;
+            return;
+        }
+    };
+
+    let mut bytes = Vec::new();
+    if file.read_to_end(&mut bytes).is_err() {
+        eprintln!("Failed to read file: {:?}", path);
+        *shared.failures.lock().unwrap() += 1;
+        return;
+    }
+
+    {
+        let mut orig = shared.total_original_bytes.lock().unwrap();
+        *orig += bytes.len() as u64;
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&bytes);
+    let hash = hasher.finalize().to_hex().to_string();
+    let webp_path = entry
+        .path()
+        .parent()
+        .unwrap()
+        .join("../")
+        .join(format!("{hash}.webp"));
+
+    if shared.dedup == DedupMode::Perceptual {
+        println!("Processing: {:?}", path);
+
+        let Some(img) = decode_for_manifest(path) else {
+            eprintln!("Failed to decode image: {:?}", path);
+            *shared.failures.lock().unwrap() += 1;
+            return;
+        };
+        let image_phash = phash::compute(&img);
+
+        // Find-and-reserve under one lock: if no existing near-duplicate is
+        // found, this image's hash is pushed into the registry immediately
+        // (rather than after encoding), so a concurrent decode worker racing
+        // on the same near-duplicate is guaranteed to see it.
+        let existing = {
+            let mut seen = shared.seen_hashes.lock().unwrap();
+            let found = seen
+                .iter()
+                .find(|(h, _)| phash::hamming_distance(*h, image_phash) <= shared.threshold)
+                .map(|(_, p)| p.clone());
+            if found.is_none() {
+                seen.push((image_phash, webp_path.clone()));
+            }
+            found
+        };
+
+        if let Some(existing_path) = existing {
+            let webp_size = fs::metadata(&existing_path).map(|m| m.len()).unwrap_or(0);
+            let recorded = if webp_size == 0 {
+                bytes.len() as u64
+            } else {
+                webp_size
+            };
+            *shared.total_webp_bytes.lock().unwrap() += recorded;
+            // The manifest's only pointer to the output file is its hash
+            // field, so it must name the webp actually reused
+            // (`existing_path`'s own hash), not this input's distinct hash.
+            let existing_hash = existing_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(str::to_string)
+                .unwrap_or(hash);
+            record_manifest_entry(
+                &shared.manifest_entries,
+                relative_path(shared.input_dir, path),
+                existing_hash,
+                recorded,
+                &img,
+            );
+            shared
+                .referenced_paths
+                .lock()
+                .unwrap()
+                .insert(existing_path);
+            return;
+        }
+
+        let _ = decoded_tx.send(DecodeJob {
+            path: path.to_path_buf(),
+            bytes,
+            hash,
+            webp_path,
+            img,
+        });
+        return;
+    }
+
+    if webp_path.exists() {
+        let webp_size = match fs::metadata(&webp_path) {
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        };
+        let recorded = if webp_size == 0 {
+            bytes.len() as u64
+        } else {
+            webp_size
+        };
+        *shared.total_webp_bytes.lock().unwrap() += recorded;
+
+        if shared.manifest_entries.is_some() {
+            if let Some(img) = decode_for_manifest(path) {
+                record_manifest_entry(
+                    &shared.manifest_entries,
+                    relative_path(shared.input_dir, path),
+                    hash,
+                    recorded,
+                    &img,
+                );
+            }
+        }
+        shared.referenced_paths.lock().unwrap().insert(webp_path);
+        return;
+    }
+
+    println!("Processing: {:?}", path);
+
+    let Some(img) = decode_for_manifest(path) else {
+        eprintln!("Failed to decode image: {:?}", path);
+        *shared.failures.lock().unwrap() += 1;
+        return;
+    };
+
+    let _ = decoded_tx.send(DecodeJob {
+        path: path.to_path_buf(),
+        bytes,
+        hash,
+        webp_path,
+        img,
+    });
+}
+
+fn encode_one(job: DecodeJob, shared: &Shared) {
+    let DecodeJob {
+        path,
+        bytes,
+        hash,
+        webp_path,
+        img,
+    } = job;
+
+    let Some(webp_bytes) = encode_output(&path, &img, shared.quality) else {
+        return;
+    };
+
+    let written = write_webp(&webp_path, &webp_bytes, bytes.len() as u64);
+    *shared.total_webp_bytes.lock().unwrap() += written;
+    record_manifest_entry(
+        &shared.manifest_entries,
+        relative_path(shared.input_dir, &path),
+        hash,
+        written,
+        &img,
+    );
+    shared.referenced_paths.lock().unwrap().insert(webp_path);
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Deletes every `{hash}.webp` file found directly inside `scan_dirs` that
+/// isn't in `referenced` (paths compared after canonicalization, since the
+/// same file can be reached through different relative forms).
+///
+/// Only files whose stem is a 64-character hex blake3 hash are candidates,
+/// since that's the only filename shape this tool ever writes — anything
+/// else (a hand-authored `logo.webp`, say) is left alone even if unreferenced.
+///
+/// Returns the number of files removed and the total bytes reclaimed.
+pub fn run(scan_dirs: &HashSet<PathBuf>, referenced: &HashSet<PathBuf>) -> (u64, u64) {
+    let referenced: HashSet<PathBuf> = referenced
+        .iter()
+        .filter_map(|p| fs::canonicalize(p).ok())
+        .collect();
+
+    let mut removed_count = 0u64;
+    let mut removed_bytes = 0u64;
+    let mut visited_dirs = HashSet::new();
+
+    for dir in scan_dirs {
+        let canonical_dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+        if !visited_dirs.insert(canonical_dir) {
+            continue;
+        }
+
+        let Ok(dir_entries) = fs::read_dir(dir) else {
+            continue;
+        };
+
+        for dir_entry in dir_entries.filter_map(Result::ok) {
+            let path = dir_entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("webp") {
+                continue;
+            }
+            if !is_content_hash_stem(&path) {
+                continue;
+            }
+
+            let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if referenced.contains(&canonical) {
+                continue;
+            }
+
+            let size = dir_entry.metadata().map(|m| m.len()).unwrap_or(0);
+            if fs::remove_file(&path).is_ok() {
+                removed_count += 1;
+                removed_bytes += size;
+            }
+        }
+    }
+
+    (removed_count, removed_bytes)
+}
+
+/// Whether `path`'s file stem looks like a 64-character hex blake3 hash,
+/// i.e. a filename this tool could plausibly have written.
+fn is_content_hash_stem(path: &std::path::Path) -> bool {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+    stem.len() == 64 && stem.bytes().all(|b| b.is_ascii_hexdigit())
+}